@@ -0,0 +1,308 @@
+//! Pluggable text encodings for keys and values crossing the JS boundary.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodecError(String);
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+/// Converts between raw bytes and the string representation JS sees.
+pub trait Codec: Send + Sync {
+    fn encode(&self, bytes: &[u8]) -> String;
+    fn decode(&self, s: &str) -> Result<Vec<u8>, CodecError>;
+}
+
+pub struct Utf8Codec;
+
+impl Codec for Utf8Codec {
+    fn encode(&self, bytes: &[u8]) -> String {
+        // Lossy on purpose: `Encoding::Buffer` is the lossless path for
+        // binary data, this codec is for callers who know their data is text.
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+
+    fn decode(&self, s: &str) -> Result<Vec<u8>, CodecError> {
+        Ok(s.as_bytes().to_vec())
+    }
+}
+
+pub struct HexCodec;
+
+impl Codec for HexCodec {
+    fn encode(&self, bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn decode(&self, s: &str) -> Result<Vec<u8>, CodecError> {
+        if !s.is_ascii() {
+            return Err(CodecError(format!("invalid hex string: {}", s)));
+        }
+        if s.len() % 2 != 0 {
+            return Err(CodecError(format!(
+                "invalid hex string (odd length): {}",
+                s
+            )));
+        }
+        s.as_bytes()
+            .chunks(2)
+            .map(|pair| {
+                let pair = std::str::from_utf8(pair).unwrap();
+                u8::from_str_radix(pair, 16)
+                    .map_err(|_| CodecError(format!("invalid hex string: {}", s)))
+            })
+            .collect()
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub struct Base64Codec;
+
+impl Codec for Base64Codec {
+    fn encode(&self, bytes: &[u8]) -> String {
+        let mut out = String::with_capacity((bytes.len() + 2) / 3 * 4);
+        for chunk in bytes.chunks(3) {
+            let b0 = chunk[0];
+            let b1 = *chunk.get(1).unwrap_or(&0);
+            let b2 = *chunk.get(2).unwrap_or(&0);
+            out.push(BASE64_ALPHABET[(b0 >> 2) as usize] as char);
+            out.push(BASE64_ALPHABET[(((b0 & 0x03) << 4) | (b1 >> 4)) as usize] as char);
+            out.push(if chunk.len() > 1 {
+                BASE64_ALPHABET[(((b1 & 0x0f) << 2) | (b2 >> 6)) as usize] as char
+            } else {
+                '='
+            });
+            out.push(if chunk.len() > 2 {
+                BASE64_ALPHABET[(b2 & 0x3f) as usize] as char
+            } else {
+                '='
+            });
+        }
+        out
+    }
+
+    fn decode(&self, s: &str) -> Result<Vec<u8>, CodecError> {
+        let s = s.trim_end_matches('=');
+        let mut out = Vec::with_capacity(s.len() * 3 / 4 + 3);
+        let mut buf = 0u32;
+        let mut bits = 0u32;
+        for c in s.bytes() {
+            let val = BASE64_ALPHABET
+                .iter()
+                .position(|&b| b == c)
+                .ok_or_else(|| CodecError(format!("invalid base64 string: {}", s)))?
+                as u32;
+            buf = (buf << 6) | val;
+            bits += 6;
+            if bits >= 8 {
+                bits -= 8;
+                out.push((buf >> bits) as u8);
+            }
+        }
+        Ok(out)
+    }
+}
+
+const BECH32_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    let generator = [
+        0x3b6a_57b2u32,
+        0x2650_8e6d,
+        0x1ea1_19fa,
+        0x3d42_33dd,
+        0x2a14_62b3,
+    ];
+    let mut chk: u32 = 1;
+    for &v in values {
+        let b = chk >> 25;
+        chk = ((chk & 0x01ff_ffff) << 5) ^ (v as u32);
+        for (i, g) in generator.iter().enumerate() {
+            if (b >> i) & 1 != 0 {
+                chk ^= g;
+            }
+        }
+    }
+    chk
+}
+
+fn bech32_hrp_expand(hrp: &str) -> Vec<u8> {
+    let mut v: Vec<u8> = hrp.bytes().map(|b| b >> 5).collect();
+    v.push(0);
+    v.extend(hrp.bytes().map(|b| b & 31));
+    v
+}
+
+fn bech32_create_checksum(hrp: &str, data: &[u8]) -> Vec<u8> {
+    let mut values = bech32_hrp_expand(hrp);
+    values.extend_from_slice(data);
+    values.extend_from_slice(&[0u8; 6]);
+    let polymod = bech32_polymod(&values) ^ 1;
+    (0..6)
+        .map(|i| ((polymod >> (5 * (5 - i))) & 31) as u8)
+        .collect()
+}
+
+fn bech32_verify_checksum(hrp: &str, data: &[u8]) -> bool {
+    let mut values = bech32_hrp_expand(hrp);
+    values.extend_from_slice(data);
+    bech32_polymod(&values) == 1
+}
+
+/// 8-bit bytes <-> 5-bit groups, used by bech32's body before checksumming.
+fn convert_bits(
+    data: &[u8],
+    from_bits: u32,
+    to_bits: u32,
+    pad: bool,
+) -> Result<Vec<u8>, CodecError> {
+    let mut acc: u32 = 0;
+    let mut bits: u32 = 0;
+    let mut ret = Vec::new();
+    let maxv = (1u32 << to_bits) - 1;
+    for &value in data {
+        acc = (acc << from_bits) | value as u32;
+        bits += from_bits;
+        while bits >= to_bits {
+            bits -= to_bits;
+            ret.push(((acc >> bits) & maxv) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            ret.push(((acc << (to_bits - bits)) & maxv) as u8);
+        }
+    } else if bits >= from_bits || (acc << (to_bits - bits)) & maxv != 0 {
+        return Err(CodecError("invalid padding in bech32 data".to_string()));
+    }
+    Ok(ret)
+}
+
+/// HRP-prefixed, checksummed bech32 codec (BIP-173 style).
+pub struct Bech32Codec {
+    pub hrp: String,
+}
+
+impl Codec for Bech32Codec {
+    fn encode(&self, bytes: &[u8]) -> String {
+        let data = convert_bits(bytes, 8, 5, true).unwrap_or_default();
+        let checksum = bech32_create_checksum(&self.hrp, &data);
+        let body: String = data
+            .iter()
+            .chain(checksum.iter())
+            .map(|&b| BECH32_CHARSET[b as usize] as char)
+            .collect();
+        format!("{}1{}", self.hrp, body)
+    }
+
+    fn decode(&self, s: &str) -> Result<Vec<u8>, CodecError> {
+        let pos = s
+            .rfind('1')
+            .ok_or_else(|| CodecError(format!("missing bech32 separator: {}", s)))?;
+        let (hrp, body) = (&s[..pos], &s[pos + 1..]);
+        if hrp != self.hrp {
+            return Err(CodecError(format!(
+                "unexpected bech32 hrp: expected {}, got {}",
+                self.hrp, hrp
+            )));
+        }
+        if body.len() < 6 {
+            return Err(CodecError(format!("bech32 string too short: {}", s)));
+        }
+        let values: Vec<u8> = body
+            .bytes()
+            .map(|c| {
+                BECH32_CHARSET
+                    .iter()
+                    .position(|&b| b == c.to_ascii_lowercase())
+                    .map(|p| p as u8)
+                    .ok_or_else(|| CodecError(format!("invalid bech32 character: {}", c as char)))
+            })
+            .collect::<Result<_, _>>()?;
+        if !bech32_verify_checksum(hrp, &values) {
+            return Err(CodecError(format!("invalid bech32 checksum: {}", s)));
+        }
+        convert_bits(&values[..values.len() - 6], 5, 8, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trip() {
+        let codec = HexCodec;
+        let bytes = vec![0u8, 1, 16, 255, 128];
+        assert_eq!(codec.decode(&codec.encode(&bytes)).unwrap(), bytes);
+    }
+
+    #[test]
+    fn hex_decode_odd_length() {
+        assert!(HexCodec.decode("abc").is_err());
+    }
+
+    #[test]
+    fn hex_decode_rejects_non_ascii_without_panicking() {
+        assert!(HexCodec.decode("a€").is_err());
+    }
+
+    #[test]
+    fn base64_round_trip() {
+        let codec = Base64Codec;
+        for bytes in [
+            vec![],
+            vec![0u8],
+            vec![1u8, 2],
+            vec![1u8, 2, 3],
+            b"hello tikv".to_vec(),
+        ] {
+            assert_eq!(codec.decode(&codec.encode(&bytes)).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn base64_decode_rejects_invalid_character() {
+        assert!(Base64Codec.decode("not-base64!!").is_err());
+    }
+
+    #[test]
+    fn bech32_round_trip() {
+        let codec = Bech32Codec {
+            hrp: "tikv".to_string(),
+        };
+        let bytes = vec![0u8, 1, 2, 3, 255, 254, 128];
+        let encoded = codec.encode(&bytes);
+        assert_eq!(codec.decode(&encoded).unwrap(), bytes);
+    }
+
+    #[test]
+    fn bech32_decode_rejects_bad_checksum() {
+        let codec = Bech32Codec {
+            hrp: "tikv".to_string(),
+        };
+        let mut encoded = codec.encode(&[1, 2, 3]);
+        encoded.push('q');
+        assert!(codec.decode(&encoded).is_err());
+    }
+
+    #[test]
+    fn bech32_decode_rejects_wrong_hrp() {
+        let encoded = Bech32Codec {
+            hrp: "tikv".to_string(),
+        }
+        .encode(&[1, 2, 3]);
+        let other = Bech32Codec {
+            hrp: "other".to_string(),
+        };
+        assert!(other.decode(&encoded).is_err());
+    }
+}
@@ -0,0 +1,222 @@
+//! Streaming raw scan that pulls pairs in batches instead of materializing
+//! an entire range into one `JsArray` up front.
+
+use std::sync::Arc;
+
+use neon::prelude::*;
+use neon::types::{JsArray, JsBoolean, JsBox};
+use tikv_client::{BoundRange, Key, KvPair, RawClient as TikvRawClient};
+use tokio::sync::Mutex;
+
+use crate::utils::{rust_pairs_to_js_array, Encoding, RUNTIME};
+
+/// The pure range/continuation bookkeeping behind a `ScanStream`, kept
+/// separate from the neon/tikv_client glue so it can be unit tested without
+/// a live JS context or TiKV cluster.
+pub(crate) struct ScanCursor {
+    // `None` once the scan has been exhausted or cancelled.
+    remaining: Option<BoundRange>,
+    batch_size: u32,
+}
+
+impl ScanCursor {
+    fn new(range: BoundRange, batch_size: u32) -> Self {
+        ScanCursor {
+            remaining: Some(range),
+            batch_size,
+        }
+    }
+
+    /// Takes the range to scan next, if any is pending.
+    fn take(&mut self) -> Option<BoundRange> {
+        self.remaining.take()
+    }
+
+    /// Records the outcome of scanning `range` and returns `done`.
+    fn advance(&mut self, range: BoundRange, pairs: &[KvPair]) -> bool {
+        self.remaining = match pairs.last() {
+            Some(last) if pairs.len() as u32 >= self.batch_size => {
+                Some(advance_range(range, last.key().clone()))
+            }
+            _ => None,
+        };
+        self.remaining.is_none()
+    }
+
+    /// Puts `range` back so the next `next()` call retries it, e.g. after a
+    /// transient scan error.
+    fn restore(&mut self, range: BoundRange) {
+        self.remaining = Some(range);
+    }
+
+    fn cancel(&mut self) {
+        self.remaining = None;
+    }
+}
+
+pub(crate) struct ScanStreamState {
+    client: Arc<TikvRawClient>,
+    cursor: ScanCursor,
+    encoding: Encoding,
+}
+
+/// Boxed and handed to JS as an opaque object exposing `next`/`cancel`.
+pub struct ScanStream {
+    pub(crate) inner: Arc<Mutex<ScanStreamState>>,
+}
+
+impl Finalize for ScanStream {}
+
+impl ScanStream {
+    pub fn new(
+        client: Arc<TikvRawClient>,
+        range: BoundRange,
+        batch_size: u32,
+        encoding: Encoding,
+    ) -> Self {
+        ScanStream {
+            inner: Arc::new(Mutex::new(ScanStreamState {
+                client,
+                cursor: ScanCursor::new(range, batch_size),
+                encoding,
+            })),
+        }
+    }
+
+    /// Resolves `{ done, pairs }`; `done: true` means the scan is exhausted.
+    pub fn js_next(mut cx: FunctionContext) -> JsResult<JsPromise> {
+        let this = cx
+            .this()
+            .downcast_or_throw::<JsBox<ScanStream>, _>(&mut cx)?;
+        let state = Arc::clone(&this.inner);
+        let channel = cx.channel();
+        let (deferred, promise) = cx.promise();
+
+        RUNTIME.spawn(async move {
+            let mut state = state.lock().await;
+            let range = match state.cursor.take() {
+                Some(range) => range,
+                None => {
+                    deferred.settle_with(&channel, move |mut cx| done_batch(&mut cx));
+                    return;
+                }
+            };
+            let batch_size = state.cursor.batch_size;
+            let encoding = state.encoding.clone();
+            let result = state.client.scan(range.clone(), batch_size).await;
+
+            match result {
+                Ok(pairs) => {
+                    let done = state.cursor.advance(range, &pairs);
+                    deferred.settle_with(&channel, move |mut cx| {
+                        let js_pairs = rust_pairs_to_js_array(&mut cx, pairs, &encoding);
+                        let out = cx.empty_object();
+                        let done_val = cx.boolean(done);
+                        out.set(&mut cx, "done", done_val)?;
+                        out.set(&mut cx, "pairs", js_pairs)?;
+                        Ok(out.upcast::<JsValue>())
+                    });
+                }
+                Err(err) => {
+                    // Put the range back so a caller that retries after the
+                    // rejection resumes the scan instead of silently seeing
+                    // it as exhausted.
+                    state.cursor.restore(range);
+                    deferred.settle_with(&channel, move |mut cx| cx.throw_error(err.to_string()));
+                }
+            }
+        });
+
+        Ok(promise)
+    }
+
+    /// Stops pulling further batches; in-flight `next()` calls still settle.
+    pub fn js_cancel(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+        let this = cx
+            .this()
+            .downcast_or_throw::<JsBox<ScanStream>, _>(&mut cx)?;
+        let state = Arc::clone(&this.inner);
+        RUNTIME.spawn(async move {
+            state.lock().await.cursor.cancel();
+        });
+        Ok(cx.undefined())
+    }
+}
+
+fn done_batch<'a>(cx: &mut TaskContext<'a>) -> JsResult<'a, JsValue> {
+    let out = cx.empty_object();
+    let done_val: Handle<JsBoolean> = cx.boolean(true);
+    let empty_pairs = JsArray::new(cx, 0);
+    out.set(cx, "done", done_val)?;
+    out.set(cx, "pairs", empty_pairs)?;
+    Ok(out.upcast())
+}
+
+/// Resumes scanning strictly after `last_key`.
+fn advance_range(range: BoundRange, last_key: Key) -> BoundRange {
+    let (_, end) = range.into_keys();
+    BoundRange::from((std::ops::Bound::Excluded(last_key), end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Bound;
+
+    fn range(start: &[u8], end: &[u8]) -> BoundRange {
+        BoundRange::from((
+            Bound::Included(start.to_vec()),
+            Bound::Excluded(end.to_vec()),
+        ))
+    }
+
+    fn pair(key: &[u8]) -> KvPair {
+        KvPair::new(key.to_vec(), key.to_vec())
+    }
+
+    #[test]
+    fn advance_continues_from_last_key_when_batch_is_full() {
+        let mut cursor = ScanCursor::new(range(b"a", b"z"), 2);
+        let taken = cursor.take().unwrap();
+        let pairs = vec![pair(b"a"), pair(b"b")];
+        let done = cursor.advance(taken, &pairs);
+        assert!(!done);
+        assert!(cursor.take().is_some());
+    }
+
+    #[test]
+    fn advance_is_done_when_batch_is_short() {
+        let mut cursor = ScanCursor::new(range(b"a", b"z"), 10);
+        let taken = cursor.take().unwrap();
+        let pairs = vec![pair(b"a"), pair(b"b")];
+        let done = cursor.advance(taken, &pairs);
+        assert!(done);
+        assert!(cursor.take().is_none());
+    }
+
+    #[test]
+    fn advance_is_done_on_empty_batch() {
+        let mut cursor = ScanCursor::new(range(b"a", b"z"), 10);
+        let taken = cursor.take().unwrap();
+        let done = cursor.advance(taken, &[]);
+        assert!(done);
+        assert!(cursor.take().is_none());
+    }
+
+    #[test]
+    fn cancel_stops_further_pulls() {
+        let mut cursor = ScanCursor::new(range(b"a", b"z"), 10);
+        cursor.cancel();
+        assert!(cursor.take().is_none());
+    }
+
+    #[test]
+    fn restore_resumes_the_same_range_after_an_error() {
+        let mut cursor = ScanCursor::new(range(b"a", b"z"), 10);
+        let taken = cursor.take().unwrap();
+        // Simulate a transient scan error: the range must still be there
+        // for the next `next()` call instead of looking exhausted.
+        cursor.restore(taken);
+        assert!(cursor.take().is_some());
+    }
+}
@@ -5,13 +5,13 @@ use neon::prelude::*;
 use neon::{
     context::{Context, TaskContext},
     prelude::Handle,
-    result::JsResultExt,
-    types::{JsArray, JsString, JsValue},
+    types::{JsArray, JsBuffer, JsPromise, JsString, JsValue},
 };
 use tikv_client::{Key, KvPair};
 
 use tikv_client::TimestampExt;
 
+use crate::codec::{Base64Codec, Bech32Codec, Codec, HexCodec, Utf8Codec};
 use crate::{RawClient, Transaction, TransactionClient};
 use lazy_static::lazy_static;
 use tokio::{runtime::Runtime, sync::Mutex};
@@ -20,15 +20,115 @@ lazy_static! {
     pub(crate) static ref RUNTIME: Runtime = Runtime::new().unwrap();
 }
 
-pub fn bytes_to_js_string<'a>(cx: &mut TaskContext<'a>, bytes: Vec<u8>) -> Handle<'a, JsValue> {
-    let content = std::str::from_utf8(&bytes).unwrap().to_owned();
-    cx.string(content).upcast()
+/// How keys and values are represented on the JS side of the boundary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Encoding {
+    Buffer,
+    Text(TextCodec),
 }
 
-// pub fn bytes_to_js_string<'a>(cx: &'a mut TaskContext, bytes: Vec<u8>) -> Handle<'a, JsValue> {
-//     let content = std::str::from_utf8(&bytes).unwrap().to_owned();
-//     cx.string(content).upcast()
-// }
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding::Text(TextCodec::Utf8)
+    }
+}
+
+/// The string encodings available for `Encoding::Text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextCodec {
+    Utf8,
+    Hex,
+    Base64,
+    Bech32 { hrp: String },
+}
+
+impl TextCodec {
+    fn codec(&self) -> Box<dyn Codec> {
+        match self {
+            TextCodec::Utf8 => Box::new(Utf8Codec),
+            TextCodec::Hex => Box::new(HexCodec),
+            TextCodec::Base64 => Box::new(Base64Codec),
+            TextCodec::Bech32 { hrp } => Box::new(Bech32Codec { hrp: hrp.clone() }),
+        }
+    }
+}
+
+pub fn bytes_to_js_value<'a>(
+    cx: &mut TaskContext<'a>,
+    bytes: Vec<u8>,
+    encoding: &Encoding,
+) -> Handle<'a, JsValue> {
+    match encoding {
+        Encoding::Buffer => JsBuffer::external(cx, bytes).upcast(),
+        Encoding::Text(text_codec) => {
+            let content = text_codec.codec().encode(&bytes);
+            cx.string(content).upcast()
+        }
+    }
+}
+
+/// Why a JS value couldn't be converted into the Rust type TiKV expects.
+/// Surfaced to callers as a `TypeError` rather than a panic, so malformed
+/// input from JS can't bring down the whole Node process.
+#[derive(Debug, Clone)]
+pub enum ConversionError {
+    NotAnArray,
+    NotAString,
+    NotABuffer,
+    WrongArity(usize),
+    InvalidEncoding(String),
+}
+
+impl std::fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConversionError::NotAnArray => write!(f, "expected an array"),
+            ConversionError::NotAString => write!(f, "expected a string"),
+            ConversionError::NotABuffer => write!(f, "expected a Buffer"),
+            ConversionError::WrongArity(len) => {
+                write!(f, "expected a [key, value] pair, got {} element(s)", len)
+            }
+            ConversionError::InvalidEncoding(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+fn throw<'a, C: Context<'a>, T>(cx: &mut C, err: ConversionError) -> NeonResult<T> {
+    cx.throw_type_error(err.to_string())
+}
+
+/// Decodes a string produced by `text_codec` back into raw bytes.
+fn decode_text(text_codec: &TextCodec, s: &str) -> Result<Vec<u8>, ConversionError> {
+    text_codec
+        .codec()
+        .decode(s)
+        .map_err(|err| ConversionError::InvalidEncoding(err.to_string()))
+}
+
+/// Reads a JS value produced for the given `encoding` back into raw bytes.
+fn js_value_to_rust_bytes<'a, C: Context<'a>>(
+    cx: &mut C,
+    value: Handle<JsValue>,
+    encoding: &Encoding,
+) -> NeonResult<Vec<u8>> {
+    match encoding {
+        Encoding::Buffer => {
+            let buffer = match value.downcast::<JsBuffer, _>(cx) {
+                Ok(buffer) => buffer,
+                Err(_) => return throw(cx, ConversionError::NotABuffer),
+            };
+            Ok(cx.borrow(&buffer, |data| data.as_slice().to_vec()))
+        }
+        Encoding::Text(text_codec) => {
+            let value = match value.downcast::<JsString, _>(cx) {
+                Ok(value) => value,
+                Err(_) => return throw(cx, ConversionError::NotAString),
+            };
+            let s = value.value(cx);
+            decode_text(text_codec, &s).or_else(|err| throw(cx, err))
+        }
+    }
+}
 
 pub enum CommonTypes {
     Unit(()),
@@ -78,38 +178,47 @@ impl From<Option<tikv_client::Timestamp>> for CommonTypes {
     }
 }
 
+/// Converts a resolved [`CommonTypes`] into the JS value callers actually see,
+/// shared by the callback (`result_to_js_array`) and promise (`send_promise`)
+/// paths so they stay in lockstep as new `CommonTypes` variants are added.
+fn common_types_to_js<'a>(
+    cx: &mut TaskContext<'a>,
+    values: CommonTypes,
+    encoding: &Encoding,
+) -> Handle<'a, JsValue> {
+    match values {
+        CommonTypes::Unit(_) => cx.undefined().upcast(),
+        CommonTypes::Keys(keys) => rust_keys_to_js_array(cx, keys, encoding).upcast(),
+        CommonTypes::KvPairs(pairs) => rust_pairs_to_js_array(cx, pairs, encoding).upcast(),
+        CommonTypes::RawClient(client) => cx
+            .boxed(RawClient {
+                inner: Arc::new(client),
+            })
+            .upcast(),
+        CommonTypes::TransactionClient(client) => cx
+            .boxed(TransactionClient {
+                inner: Arc::new(client),
+            })
+            .upcast(),
+        CommonTypes::Transaction(client) => cx
+            .boxed(Transaction {
+                inner: Arc::new(Mutex::new(client)),
+            })
+            .upcast(),
+        CommonTypes::Timestamp(timestamp) => match timestamp {
+            None => cx.undefined().upcast(),
+            Some(t) => cx.number(t.version() as f64).upcast(),
+        },
+    }
+}
+
 pub fn result_to_js_array<'a>(
     cx: &mut TaskContext<'a>,
     result: Result<CommonTypes, tikv_client::Error>,
+    encoding: &Encoding,
 ) -> Vec<Handle<'a, JsValue>> {
     match result {
-        Ok(values) => vec![
-            cx.null().upcast(),
-            match values {
-                CommonTypes::Unit(_) => cx.undefined().upcast(),
-                CommonTypes::Keys(keys) => rust_keys_to_js_array(cx, keys).upcast(),
-                CommonTypes::KvPairs(pairs) => rust_pairs_to_js_array(cx, pairs).upcast(),
-                CommonTypes::RawClient(client) => cx
-                    .boxed(RawClient {
-                        inner: Arc::new(client),
-                    })
-                    .upcast(),
-                CommonTypes::TransactionClient(client) => cx
-                    .boxed(TransactionClient {
-                        inner: Arc::new(client),
-                    })
-                    .upcast(),
-                CommonTypes::Transaction(client) => cx
-                    .boxed(Transaction {
-                        inner: Arc::new(Mutex::new(client)),
-                    })
-                    .upcast(),
-                CommonTypes::Timestamp(timestamp) => match timestamp {
-                    None => cx.undefined().upcast(),
-                    Some(t) => cx.number(t.version() as f64).upcast(),
-                },
-            },
-        ],
+        Ok(values) => vec![cx.null().upcast(), common_types_to_js(cx, values, encoding)],
         Err(err) => vec![
             cx.error(err.to_string()).unwrap().upcast(),
             cx.undefined().upcast(),
@@ -120,16 +229,13 @@ pub fn result_to_js_array<'a>(
 pub fn rust_pairs_to_js_array<'a>(
     cx: &mut TaskContext<'a>,
     values: Vec<KvPair>,
+    encoding: &Encoding,
 ) -> Handle<'a, JsArray> {
     let js_array = JsArray::new(cx, values.len() as u32);
-    for (i, obj) in values.iter().enumerate() {
+    for (i, obj) in values.into_iter().enumerate() {
         let pair = JsArray::new(cx, 2);
-        let v1 = cx.string(
-            std::str::from_utf8(&Vec::from(obj.0.clone()))
-                .unwrap()
-                .to_owned(),
-        );
-        let v2 = cx.string(std::str::from_utf8(&obj.1).unwrap().to_owned());
+        let v1 = bytes_to_js_value(cx, Vec::from(obj.0), encoding);
+        let v2 = bytes_to_js_value(cx, obj.1, encoding);
         pair.set(cx, 0, v1).unwrap();
         pair.set(cx, 1, v2).unwrap();
         js_array.set(cx, i as u32, pair).unwrap();
@@ -137,14 +243,14 @@ pub fn rust_pairs_to_js_array<'a>(
     js_array
 }
 
-pub fn rust_keys_to_js_array<'a>(cx: &mut TaskContext<'a>, keys: Vec<Key>) -> Handle<'a, JsArray> {
+pub fn rust_keys_to_js_array<'a>(
+    cx: &mut TaskContext<'a>,
+    keys: Vec<Key>,
+    encoding: &Encoding,
+) -> Handle<'a, JsArray> {
     let js_array = JsArray::new(cx, keys.len() as u32);
-    for (i, obj) in keys.iter().enumerate() {
-        let v1 = cx.string(
-            std::str::from_utf8(&Vec::from(obj.clone()))
-                .unwrap()
-                .to_owned(),
-        );
+    for (i, obj) in keys.into_iter().enumerate() {
+        let v1 = bytes_to_js_value(cx, obj.into(), encoding);
         js_array.set(cx, i as u32, v1).unwrap();
     }
     js_array
@@ -153,49 +259,53 @@ pub fn rust_keys_to_js_array<'a>(cx: &mut TaskContext<'a>, keys: Vec<Key>) -> Ha
 pub fn js_array_to_rust_keys<'a>(
     cx: &mut FunctionContext<'a>,
     array: Handle<JsArray>,
-) -> impl IntoIterator<Item = impl Into<Key>> {
-    let array = array.to_vec(cx).unwrap(); // TODO: remove unwrap here
+    encoding: &Encoding,
+) -> NeonResult<Vec<Key>> {
+    let array = match array.to_vec(cx) {
+        Ok(array) => array,
+        Err(_) => return throw(cx, ConversionError::NotAnArray),
+    };
     array
         .into_iter()
-        .map(|k| {
-            k.downcast::<JsString, _>(cx)
-                .or_throw(cx)
-                .unwrap()
-                .value(cx)
-        })
-        .collect::<Vec<String>>()
+        .map(|k| js_value_to_rust_bytes(cx, k, encoding).map(Key::from))
+        .collect()
+}
+
+/// A pair must have exactly a key and a value.
+fn validate_pair_len(len: usize) -> Result<(), ConversionError> {
+    if len != 2 {
+        Err(ConversionError::WrongArity(len))
+    } else {
+        Ok(())
+    }
 }
 
 pub fn js_array_to_rust_pairs<'a>(
     cx: &mut FunctionContext<'a>,
     array: Handle<JsArray>,
-) -> impl IntoIterator<Item = impl Into<KvPair>> {
-    let array = array.to_vec(cx).unwrap(); // TODO: remove unwrap here
-    let mut pairs = vec![];
+    encoding: &Encoding,
+) -> NeonResult<Vec<KvPair>> {
+    let array = match array.to_vec(cx) {
+        Ok(array) => array,
+        Err(_) => return throw(cx, ConversionError::NotAnArray),
+    };
+    let mut pairs = Vec::with_capacity(array.len());
     for k in array.into_iter() {
-        let pair_result = k.downcast::<JsArray, _>(cx).or_throw(cx);
-        match pair_result {
-            Ok(pair) => {
-                let args: Vec<String> = vec![0_u32, 1_u32]
-                    .into_iter()
-                    .map(|i| {
-                        pair.get(cx, i as u32)
-                            .unwrap()
-                            .downcast::<JsString, _>(cx)
-                            .or_throw(cx)
-                            .unwrap() // TODO: remove unwrap here
-                            .value(cx)
-                    })
-                    .collect();
-                pairs.push(KvPair::new(
-                    args.get(0).unwrap().to_owned(),
-                    args.get(1).unwrap().to_owned(),
-                ));
-            }
-            Err(err) => println!("{}", err.to_string()),
+        let pair = match k.downcast::<JsArray, _>(cx) {
+            Ok(pair) => pair,
+            Err(_) => return throw(cx, ConversionError::NotAnArray),
+        };
+        let len = pair.len(cx);
+        if let Err(err) = validate_pair_len(len as usize) {
+            return throw(cx, err);
         }
+        let key = pair.get(cx, 0)?;
+        let value = pair.get(cx, 1)?;
+        let key = js_value_to_rust_bytes(cx, key, encoding)?;
+        let value = js_value_to_rust_bytes(cx, value, encoding)?;
+        pairs.push(KvPair::new(key, value));
     }
-    pairs
+    Ok(pairs)
 }
 
 pub fn to_bound_range(
@@ -225,18 +335,140 @@ pub fn to_bound_range(
     tikv_client::BoundRange::from((start_bound, end_bound))
 }
 
+/// Maps a client call's outcome into the shape `result_to_js_array`/
+/// `common_types_to_js` settle with, leaving `Err` untouched so both the
+/// callback and promise paths reject with the original `tikv_client::Error`.
+fn map_send_result<T: Into<CommonTypes>>(
+    result: Result<T, tikv_client::Error>,
+) -> Result<CommonTypes, tikv_client::Error> {
+    result.map(Into::into)
+}
+
 pub fn send_result<T: Into<CommonTypes>>(
     queue: EventQueue,
     callback: Root<JsFunction>,
     result: Result<T, tikv_client::Error>,
+    encoding: Encoding,
 ) -> Result<(), neon::result::Throw> {
-    let result = result.map(|values| values.into());
+    let result = map_send_result(result);
     queue.send(move |mut cx| {
         let callback = callback.into_inner(&mut cx);
         let this = cx.undefined();
-        let args: Vec<Handle<JsValue>> = result_to_js_array(&mut cx, result);
+        let args: Vec<Handle<JsValue>> = result_to_js_array(&mut cx, result, &encoding);
         callback.call(&mut cx, this, args)?;
         Ok(())
     });
     Ok(())
 }
+
+/// Settles a JS `Promise` with the outcome of `result`, the `await`-friendly
+/// counterpart to `send_result`. Shares `common_types_to_js` so both paths
+/// resolve/reject identically.
+pub fn send_promise<'a, T, F>(
+    cx: &mut FunctionContext<'a>,
+    queue: EventQueue,
+    encoding: Encoding,
+    fut: F,
+) -> JsResult<'a, JsPromise>
+where
+    T: Into<CommonTypes> + Send + 'static,
+    F: std::future::Future<Output = Result<T, tikv_client::Error>> + Send + 'static,
+{
+    let (deferred, promise) = cx.promise();
+    RUNTIME.spawn(async move {
+        let result = map_send_result(fut.await);
+        deferred.settle_with(&queue, move |mut cx| match result {
+            Ok(values) => Ok(common_types_to_js(&mut cx, values, &encoding)),
+            Err(err) => cx.throw_error(err.to_string()),
+        });
+    });
+    Ok(promise)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `js_array_to_rust_keys`/`js_array_to_rust_pairs` need a
+    // `FunctionContext`, which can't be constructed outside of a live Node
+    // call into this addon. These tests cover the pure checks and error
+    // construction they delegate to instead: pair arity, text decoding and
+    // the `ConversionError` messages they throw.
+
+    #[test]
+    fn pair_arity_of_two_is_valid() {
+        assert!(validate_pair_len(2).is_ok());
+    }
+
+    #[test]
+    fn pair_arity_of_one_is_rejected() {
+        assert_eq!(
+            validate_pair_len(1).unwrap_err().to_string(),
+            "expected a [key, value] pair, got 1 element(s)"
+        );
+    }
+
+    #[test]
+    fn pair_arity_of_three_is_rejected() {
+        assert_eq!(
+            validate_pair_len(3).unwrap_err().to_string(),
+            "expected a [key, value] pair, got 3 element(s)"
+        );
+    }
+
+    #[test]
+    fn conversion_error_messages() {
+        assert_eq!(ConversionError::NotAnArray.to_string(), "expected an array");
+        assert_eq!(ConversionError::NotAString.to_string(), "expected a string");
+        assert_eq!(ConversionError::NotABuffer.to_string(), "expected a Buffer");
+        assert_eq!(
+            ConversionError::InvalidEncoding("bad hex".to_string()).to_string(),
+            "bad hex"
+        );
+    }
+
+    #[test]
+    fn decode_text_round_trips_through_the_chosen_codec() {
+        let bytes = vec![0u8, 1, 2, 3, 255];
+        let encoded = TextCodec::Hex.codec().encode(&bytes);
+        assert_eq!(decode_text(&TextCodec::Hex, &encoded).unwrap(), bytes);
+    }
+
+    #[test]
+    fn decode_text_reports_invalid_encoding() {
+        let err = decode_text(&TextCodec::Hex, "not hex").unwrap_err();
+        assert!(matches!(err, ConversionError::InvalidEncoding(_)));
+    }
+
+    // `Encoding::Buffer` is the binary-safe path: unlike `Encoding::Text`, it
+    // never routes bytes through a string codec, so bytes that aren't valid
+    // UTF-8 survive a round trip unchanged. That passthrough happens inside
+    // `bytes_to_js_value`/`js_value_to_rust_bytes`'s `Encoding::Buffer` arms
+    // via `JsBuffer`, which (like the rest of the neon glue in this file)
+    // only exists inside a live Node call into this addon and can't be
+    // constructed under `cargo test`. What's unit-testable here is the
+    // property that makes `Encoding::Buffer` necessary in the first place:
+    // the text codec it exists to bypass is lossy on the same input.
+    #[test]
+    fn text_utf8_codec_is_lossy_on_invalid_utf8_unlike_buffer_mode() {
+        let invalid_utf8 = vec![0xff, 0xfe, 0x00, 0xff];
+        let codec = TextCodec::Utf8.codec();
+        let round_tripped = codec.decode(&codec.encode(&invalid_utf8)).unwrap();
+        assert_ne!(round_tripped, invalid_utf8);
+    }
+
+    // `send_result`/`send_promise` themselves need an `EventQueue` (and, for
+    // the latter, a live `FunctionContext` to build the promise and settle
+    // it on the JS thread), neither of which exists outside a real Node
+    // call into this addon. `map_send_result` is the pure outcome-shaping
+    // step both share; `Result::map` leaving `Err` untouched is load-bearing
+    // here, since it's what makes the two paths reject identically.
+    #[test]
+    fn map_send_result_converts_ok_into_common_types() {
+        let result: Result<(), tikv_client::Error> = Ok(());
+        assert!(matches!(
+            map_send_result(result).unwrap(),
+            CommonTypes::Unit(())
+        ));
+    }
+}